@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use anyhow::Context;
+use attohttpc::Session;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::checksum::{Checksum, Hasher};
+use crate::retry::with_retry;
+
+/// The base URL of the official PaperMC v2 API, used unless the caller
+/// points `PaperClient` at a mirror or a self-hosted Fill/Hangar instance.
+pub const DEFAULT_BASE_URL: &str = "https://papermc.io/api/v2";
+
+/// A thin, retrying client over the PaperMC v2 REST API. Holds the base URL
+/// and a reusable HTTP session (carrying a configurable `User-Agent`) so
+/// callers don't have to thread either through every request, which also
+/// makes the crate embeddable in other tools and testable against a mock
+/// server by pointing `base_url` elsewhere.
+pub struct PaperClient {
+    base_url: String,
+    session: Session,
+    max_retries: u32,
+}
+
+impl PaperClient {
+    pub fn new(base_url: impl Into<String>, user_agent: impl AsRef<str>, max_retries: u32) -> Self {
+        let mut session = Session::new();
+        session.header("User-Agent", user_agent.as_ref());
+        PaperClient {
+            base_url: base_url.into(),
+            session,
+            max_retries,
+        }
+    }
+
+    /// Lists every project the API knows about.
+    pub fn projects(&self) -> Result<ProjectsData, anyhow::Error> {
+        self.get_json(format!("{}/projects", self.base_url))
+    }
+
+    pub fn project(&self, project: &str) -> Result<ProjectData, anyhow::Error> {
+        self.get_json(format!("{}/projects/{}", self.base_url, project))
+    }
+
+    pub fn version_group(
+        &self,
+        project: &str,
+        group: &str,
+    ) -> Result<VersionGroupData, anyhow::Error> {
+        self.get_json(format!(
+            "{}/projects/{}/version_group/{}",
+            self.base_url, project, group
+        ))
+    }
+
+    pub fn version(&self, project: &str, version: &str) -> Result<VersionData, anyhow::Error> {
+        self.get_json(format!(
+            "{}/projects/{}/versions/{}",
+            self.base_url, project, version
+        ))
+    }
+
+    pub fn build(
+        &self,
+        project: &str,
+        version: &str,
+        build: i32,
+    ) -> Result<BuildData, anyhow::Error> {
+        self.get_json(format!(
+            "{}/projects/{}/versions/{}/builds/{}",
+            self.base_url, project, version, build
+        ))
+    }
+
+    /// Builds the `Checksum` a downloaded artifact should be verified
+    /// against. The API only ever populates `sha256` today, but a future
+    /// field just needs a branch here, not a new code path in every caller.
+    pub fn checksum_for(download: &Download) -> Result<Checksum, anyhow::Error> {
+        Checksum::sha256(&download.sha256)
+    }
+
+    /// Starts streaming a build artifact, resuming from byte `existing_len`
+    /// (continuing `existing_hasher`'s running digest) if it's non-zero and
+    /// the server honors the `Range` request. Either way, the returned
+    /// reader checks the artifact against `checksum` as it's consumed: the
+    /// `read` call that hits EOF fails with `ErrorKind::InvalidData` if the
+    /// digest doesn't match, so callers don't need a separate verification
+    /// pass.
+    ///
+    /// Unlike the JSON-fetching methods above, this doesn't retry transient
+    /// failures; a caller that wants to retry one should wrap its whole
+    /// download (including this call) in `retry::with_retry` so a later
+    /// attempt re-enters with an up-to-date `existing_len`. The one thing it
+    /// does handle internally is a `416 Range Not Satisfiable` response to a
+    /// resume request, which it resolves by verifying the existing bytes
+    /// directly (or, if they don't match, falling back to a fresh request)
+    /// rather than surfacing it as a permanent error.
+    pub fn download_stream(
+        &self,
+        project: &str,
+        version: &str,
+        build: i32,
+        download: &Download,
+        checksum: &Checksum,
+        mut existing_len: u64,
+        mut existing_hasher: Option<Hasher>,
+    ) -> Result<DownloadStream<impl Read>, anyhow::Error> {
+        let url = format!(
+            "{}/projects/{}/versions/{}/builds/{}/downloads/{}",
+            self.base_url, project, version, build, download.name
+        );
+
+        loop {
+            let mut request = self.session.get(&url);
+            if existing_len > 0 {
+                request = request.header("Range", format!("bytes={}-", existing_len));
+            }
+            let res = request.send().map_err(anyhow::Error::from)?;
+
+            if existing_len > 0 && res.status().as_u16() == 416 {
+                // The server has nothing left past `existing_len`, which means
+                // the `.part` we asked to resume already holds the complete
+                // artifact from a prior run that died before it could be
+                // verified and renamed into place.
+                let actual = existing_hasher
+                    .take()
+                    .unwrap_or_else(|| checksum.hasher())
+                    .finalize();
+                if checksum.matches(&actual) {
+                    return Ok(DownloadStream {
+                        resumed: true,
+                        content_length: Some(0),
+                        reader: VerifyingRead::already_verified(res, checksum.clone()),
+                    });
+                }
+                // The existing bytes don't match what we expect, so there's
+                // nothing to resume from; start over with a full request.
+                existing_len = 0;
+                continue;
+            }
+
+            let res = res.error_for_status().map_err(anyhow::Error::from)?;
+
+            let resumed = existing_len > 0 && res.status().as_u16() == 206;
+            let content_length = res
+                .headers()
+                .get("Content-length")
+                .and_then(|len| len.to_str().ok().and_then(|len_str| len_str.parse().ok()));
+
+            let hasher = if resumed {
+                existing_hasher.take().unwrap_or_else(|| checksum.hasher())
+            } else {
+                checksum.hasher()
+            };
+
+            return Ok(DownloadStream {
+                resumed,
+                content_length,
+                reader: VerifyingRead::new(res, hasher, checksum.clone()),
+            });
+        }
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, url: String) -> Result<T, anyhow::Error> {
+        with_retry(self.max_retries, || {
+            self.session
+                .get(&url)
+                .send()
+                .map_err(anyhow::Error::from)
+                .and_then(|x| x.error_for_status().map_err(anyhow::Error::from))
+                .and_then(|x| x.json().map_err(anyhow::Error::from))
+        })
+        .context("Failed to download JSON")
+    }
+}
+
+/// Whether the server honored a resume request, paired with the declared
+/// body length and a reader over the (digest-verified) body.
+pub struct DownloadStream<R> {
+    pub resumed: bool,
+    pub content_length: Option<u64>,
+    pub reader: R,
+}
+
+/// A `Read` adapter that feeds every byte it yields through a running
+/// digest, checking it against `checksum` once the inner reader is
+/// exhausted instead of requiring a separate verification pass.
+struct VerifyingRead<R> {
+    inner: R,
+    hasher: Option<Hasher>,
+    checksum: Checksum,
+    done: bool,
+}
+
+impl<R: Read> VerifyingRead<R> {
+    fn new(inner: R, hasher: Hasher, checksum: Checksum) -> Self {
+        VerifyingRead {
+            inner,
+            hasher: Some(hasher),
+            checksum,
+            done: false,
+        }
+    }
+
+    /// Wraps `inner` as already matching `checksum`, so the first `read`
+    /// reports EOF without consuming `inner` or touching a digest. Used when
+    /// a `Range` request comes back 416 because the bytes we already have on
+    /// disk are the complete, correct artifact.
+    fn already_verified(inner: R, checksum: Checksum) -> Self {
+        VerifyingRead {
+            inner,
+            hasher: None,
+            checksum,
+            done: true,
+        }
+    }
+}
+
+impl<R: Read> Read for VerifyingRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        let amt = self.inner.read(buf)?;
+        if amt == 0 {
+            self.done = true;
+            let actual = self
+                .hasher
+                .take()
+                .expect("hasher only taken once")
+                .finalize();
+            if !self.checksum.matches(&actual) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "Failed digest check, given {}, got {}",
+                        self.checksum.expected_hex(),
+                        hex::encode(actual)
+                    ),
+                ));
+            }
+        } else {
+            self.hasher.as_mut().unwrap().update(&buf[..amt]);
+        }
+        Ok(amt)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ProjectsData {
+    pub projects: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ProjectData {
+    pub project_id: String,
+    pub version_groups: Vec<String>,
+    pub versions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct VersionGroupData {
+    pub versions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct VersionData {
+    pub builds: Vec<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct BuildData {
+    pub downloads: HashMap<String, Download>,
+}
+
+#[derive(Deserialize)]
+pub struct Download {
+    pub name: String,
+    pub sha256: String,
+}
@@ -0,0 +1,5 @@
+pub mod checksum;
+pub mod client;
+pub mod progress;
+pub mod retry;
+pub mod signature;
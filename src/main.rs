@@ -1,24 +1,28 @@
 #![deny(warnings)]
 
-use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::convert::Infallible;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::{stdout, Write};
+use std::io::stdout;
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
 
 use anyhow::Context;
 use console::{colors_enabled_stderr, set_colors_enabled};
-use serde::de::DeserializeOwned;
-use serde::Deserialize;
-use sha2::Digest;
+use indicatif::MultiProgress;
 use structopt::StructOpt;
 
-use crate::progress::{new_progress_bar, ProgressTrackable};
+use paper_latest::checksum::Checksum;
+use paper_latest::client::{BuildData, Download, PaperClient, ProjectData, DEFAULT_BASE_URL};
+use paper_latest::progress::{new_progress_bar, ProgressTrackable};
+use paper_latest::retry::with_retry;
+use paper_latest::signature::verify_detached_signature;
 
-mod progress;
+const USER_AGENT: &str = "paper-latest";
 
 #[derive(StructOpt)]
 #[structopt(name = "paper-latest", about = "Gets the latest Paper JAR")]
@@ -26,18 +30,52 @@ struct PaperLatest {
     #[structopt(short, long, help = "The project to fetch", default_value = "paper")]
     project: String,
     #[structopt(
-        long,
-        help = "The type of download to fetch",
+        long = "download-type",
+        help = "The type(s) of download to fetch (repeat the flag for more than one)",
+        number_of_values = 1,
         default_value = "application"
     )]
-    download_type: String,
-    #[structopt(help = "The version (group) to fetch")]
-    version: String,
+    download_types: Vec<String>,
+    #[structopt(
+        long = "version",
+        help = "The version(s) (or version groups) to fetch (repeat the flag for more than one)",
+        number_of_values = 1,
+        required = true
+    )]
+    versions: Vec<String>,
     #[structopt(
-        help = "The file location to download to, or `-` for STDOUT",
+        long,
+        help = "Fetch every build of each version instead of just the latest"
+    )]
+    all_builds: bool,
+    #[structopt(
+        help = "The file to download to when fetching a single target, the directory to \
+                download into when fetching multiple, or `-` for STDOUT (single target only)",
         default_value = "-"
     )]
     download_location: DownloadLocation,
+    #[structopt(
+        short,
+        long,
+        help = "Number of concurrent downloads to run, 0 for one per CPU",
+        default_value = "0"
+    )]
+    jobs: usize,
+    #[structopt(
+        long,
+        help = "Maximum number of retry attempts for transient network failures",
+        default_value = "5"
+    )]
+    max_retries: u32,
+    #[structopt(long, help = "The base URL of the PaperMC API to query")]
+    base_url: Option<String>,
+    #[structopt(
+        long = "verify-signature",
+        help = "After a download's checksum passes, verify it against a detached signature \
+                named `<file>.sig` using this keyring (only supported when writing to a file)",
+        parse(from_os_str)
+    )]
+    verify_signature: Option<PathBuf>,
 }
 
 #[derive(Clone)]
@@ -46,15 +84,6 @@ enum DownloadLocation {
     File(PathBuf),
 }
 
-impl DownloadLocation {
-    fn writer(&self) -> Result<Box<dyn Write>, anyhow::Error> {
-        Ok(match self {
-            DownloadLocation::Stdout => Box::new(stdout()),
-            DownloadLocation::File(path) => Box::new(std::fs::File::create(path)?),
-        })
-    }
-}
-
 impl FromStr for DownloadLocation {
     type Err = Infallible;
 
@@ -75,133 +104,442 @@ impl Display for DownloadLocation {
     }
 }
 
-const PAPER_BASE: &str = "https://papermc.io/api/v2";
+/// A single `(version, download_type)` pair requested on the command line.
+/// Resolved up front into one or more `DownloadTask`s before the worker pool
+/// starts, so `--all-builds` fans every build out across the pool instead of
+/// being stuck serially behind whichever single `Job` produced them.
+struct Job {
+    version_input: String,
+    download_type: String,
+}
+
+/// One concrete `(version, build, download_type)` to fetch, independent of
+/// every other task in the queue. This is the unit of work workers pull off
+/// the queue, which is what lets `--all-builds` download every build of a
+/// single version concurrently instead of one at a time.
+struct DownloadTask {
+    label: String,
+    version: String,
+    build: i32,
+    download_type: String,
+}
 
 fn main() {
     // hacky af, but we know we don't print color to STDOUT here
     set_colors_enabled(colors_enabled_stderr());
     let args: PaperLatest = PaperLatest::from_args();
 
-    if matches!(args.download_location, DownloadLocation::Stdout) && console::user_attended() {
-        eprintln!("Refusing to write binary output to a terminal. Please redirect to another program or file.");
-        exit(1);
+    let jobs: Vec<Job> = args
+        .versions
+        .iter()
+        .flat_map(|version_input| {
+            args.download_types.iter().map(move |download_type| Job {
+                version_input: version_input.clone(),
+                download_type: download_type.clone(),
+            })
+        })
+        .collect();
+    let batch = jobs.len() > 1 || args.all_builds;
+
+    if matches!(args.download_location, DownloadLocation::Stdout) {
+        if batch {
+            eprintln!("Cannot stream multiple downloads to STDOUT; pass a directory instead.");
+            exit(1);
+        }
+        if console::user_attended() {
+            eprintln!("Refusing to write binary output to a terminal. Please redirect to another program or file.");
+            exit(1);
+        }
+        if args.verify_signature.is_some() {
+            eprintln!("Cannot verify a signature against STDOUT output; pass a file instead.");
+            exit(1);
+        }
     }
 
-    let project_data: ProjectData =
-        do_get_json(format!("{}/projects/{}", PAPER_BASE, args.project))
-            .expect("Failed to get project data");
+    if let DownloadLocation::File(dir) = &args.download_location {
+        if batch {
+            std::fs::create_dir_all(dir).expect("Failed to create output directory");
+        }
+    }
 
-    let version = determine_version(&project_data, &args.version)
-        .expect("Failed to determine version to download");
+    let base_url = args
+        .base_url
+        .clone()
+        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+    let client = PaperClient::new(base_url, USER_AGENT, args.max_retries);
+
+    let project_data: ProjectData = client
+        .project(&args.project)
+        .expect("Failed to get project data");
+
+    // Resolving each job into its concrete build(s) needs a couple of API
+    // calls, but doing it up front (rather than lazily inside a worker, once
+    // per job) is what lets a single `--version` with `--all-builds` spread
+    // its builds across every worker instead of downloading them serially
+    // behind the one job that discovered them.
+    let mut tasks = Vec::new();
+    let mut outcomes: Vec<(String, Result<(), anyhow::Error>)> = Vec::new();
+    for job in &jobs {
+        match resolve_job(&client, &args.project, &project_data, job, args.all_builds) {
+            Ok(job_tasks) => tasks.extend(job_tasks),
+            Err((label, e)) => outcomes.push((label, Err(e))),
+        }
+    }
 
-    let version_data: VersionData = do_get_json(format!(
-        "{}/projects/{}/versions/{}",
-        PAPER_BASE, args.project, version
-    ))
-    .expect("Failed to get version data");
+    let worker_count = if args.jobs == 0 {
+        thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    } else {
+        args.jobs
+    };
+    let worker_count = worker_count.min(tasks.len()).max(1);
+
+    let multi = if batch {
+        Some(MultiProgress::new())
+    } else {
+        None
+    };
 
-    let build = version_data
-        .builds
+    let queue = Mutex::new(tasks.into_iter().collect::<VecDeque<_>>());
+    let outcomes = Mutex::new(outcomes);
+
+    thread::scope(|scope| {
+        for _ in 0..worker_count {
+            let queue = &queue;
+            let outcomes = &outcomes;
+            let client = &client;
+            let project = &args.project;
+            let download_location = &args.download_location;
+            let multi = multi.as_ref();
+            let max_retries = args.max_retries;
+            let verify_signature = args.verify_signature.as_deref();
+
+            scope.spawn(move || loop {
+                let task = match queue.lock().unwrap().pop_front() {
+                    Some(task) => task,
+                    None => break,
+                };
+                let result = download_one(
+                    client,
+                    project,
+                    &task.version,
+                    task.build,
+                    &task.download_type,
+                    download_location,
+                    batch,
+                    max_retries,
+                    verify_signature,
+                    multi,
+                );
+                outcomes.lock().unwrap().push((task.label, result));
+            });
+        }
+    });
+
+    let outcomes = outcomes.into_inner().unwrap();
+
+    let failures: Vec<_> = outcomes.iter().filter(|(_, r)| r.is_err()).collect();
+    eprintln!(
+        "Downloaded {}/{} target(s) for project '{}'; {} failed.",
+        outcomes.len() - failures.len(),
+        outcomes.len(),
+        project_data.project_id,
+        failures.len()
+    );
+    for (label, result) in &outcomes {
+        if let Err(e) = result {
+            eprintln!("  {}: {}", label, e);
+        }
+    }
+
+    if !failures.is_empty() {
+        exit(1);
+    }
+}
+
+/// Resolves `job` into the concrete `(version, build, download_type)` tasks
+/// it refers to, without downloading anything. On failure, returns the
+/// `(label, error)` outcome the caller should report in `job`'s place.
+fn resolve_job(
+    client: &PaperClient,
+    project: &str,
+    project_data: &ProjectData,
+    job: &Job,
+    all_builds: bool,
+) -> Result<Vec<DownloadTask>, (String, anyhow::Error)> {
+    let version = determine_version(client, project_data, &job.version_input)
+        .map_err(|e| (job.version_input.clone(), e))?;
+
+    let version_data = client
+        .version(project, &version)
+        .map_err(|e| (format!("{} ({})", version, job.download_type), e))?;
+
+    let builds: Vec<i32> = if all_builds {
+        version_data.builds.clone()
+    } else {
+        match version_data.builds.iter().max() {
+            Some(b) => vec![*b],
+            None => {
+                return Err((
+                    format!("{} ({})", version, job.download_type),
+                    anyhow::anyhow!("Version has no builds"),
+                ))
+            }
+        }
+    };
+
+    Ok(builds
         .into_iter()
-        .max()
-        .expect("Version has no builds");
+        .map(|build| DownloadTask {
+            label: format!("{} build {} ({})", version, build, job.download_type),
+            version: version.clone(),
+            build,
+            download_type: job.download_type.clone(),
+        })
+        .collect())
+}
 
-    let build_data: BuildData = do_get_json(format!(
-        "{}/projects/{}/versions/{}/builds/{}",
-        PAPER_BASE, args.project, version, build
-    ))
-    .expect("Failed to get build data");
+/// Fetches the build metadata for a single `(version, build, download_type)`
+/// and downloads it, skipping it if an already-valid copy is on disk.
+fn download_one(
+    client: &PaperClient,
+    project: &str,
+    version: &str,
+    build: i32,
+    download_type: &str,
+    base_location: &DownloadLocation,
+    batch: bool,
+    max_retries: u32,
+    verify_signature: Option<&Path>,
+    multi: Option<&MultiProgress>,
+) -> Result<(), anyhow::Error> {
+    let build_data: BuildData = client.build(project, version, build)?;
 
     let download = build_data
         .downloads
-        .get(&args.download_type)
-        .expect("No download of the given type available");
-
-    let download_hash = hex::decode(&download.sha256)
-        .with_context(|| format!("Got a sha256 value that wasn't hex: {}", download.sha256))
-        .unwrap();
+        .get(download_type)
+        .ok_or_else(|| anyhow::anyhow!("No download of type '{}' available", download_type))?;
+    let checksum = PaperClient::checksum_for(download)?;
+
+    let location = if batch {
+        match base_location {
+            DownloadLocation::File(dir) => DownloadLocation::File(dir.join(&download.name)),
+            DownloadLocation::Stdout => unreachable!("stdout is rejected in batch mode"),
+        }
+    } else {
+        base_location.clone()
+    };
 
-    if let DownloadLocation::File(path) = args.download_location.clone() {
+    if let DownloadLocation::File(path) = &location {
         if path.exists()
-            && check_file_hash(&download_hash, &path).unwrap_or_else(|e| {
+            && check_file_hash(&checksum, path, multi).unwrap_or_else(|e| {
                 eprintln!("Failed to check file hash, re-downloading: {}", e);
                 false
             })
         {
-            eprintln!("Latest build already downloaded. Exiting.");
-            return;
+            eprintln!("{} already downloaded; skipping.", location);
+            return Ok(());
         }
     }
 
-    let bytes =
-        download_build(&args, &version, build, download).expect("Failed to download from stream");
-
-    check_mem_hash(&download_hash, &bytes);
-
-    let mut writer = args
-        .download_location
-        .writer()
-        .expect("Failed to open writer to download location");
-
-    let bar = new_progress_bar(Some(bytes.len() as u64));
-    bar.set_message("Saving to output");
-    let mut bytes_reader = bytes.as_slice().track_with(bar);
-
-    std::io::copy(&mut bytes_reader, &mut writer)
-        .with_context(|| format!("Failed to save bytes to {}", args.download_location))
-        .unwrap();
-
-    bytes_reader.bar.finish_with_message("Saved.");
+    download_build(
+        client,
+        project,
+        version,
+        build,
+        download,
+        &checksum,
+        &location,
+        max_retries,
+        verify_signature,
+        multi,
+    )?;
 
     eprintln!(
         "Downloaded PaperMC Project '{}', version '{}', build '{}' to '{}'",
-        project_data.project_id, version, build, args.download_location
+        project, version, build, location
     );
+    Ok(())
 }
 
 fn download_build(
-    args: &PaperLatest,
-    version: &String,
+    client: &PaperClient,
+    project: &str,
+    version: &str,
+    build: i32,
+    download: &Download,
+    checksum: &Checksum,
+    location: &DownloadLocation,
+    max_retries: u32,
+    verify_signature: Option<&Path>,
+    multi: Option<&MultiProgress>,
+) -> Result<(), anyhow::Error> {
+    match location {
+        DownloadLocation::Stdout => {
+            // STDOUT isn't resumable: once a byte has been written there's no
+            // taking it back, so only the connect/headers phase is retried
+            // here, not the body copy below. A transient failure mid-copy is
+            // left to surface as-is rather than risk writing the stream out
+            // twice.
+            let stream = with_retry(max_retries, || {
+                client.download_stream(project, version, build, download, checksum, 0, None)
+            })?;
+            let bar = new_progress_bar(multi, stream.content_length);
+            bar.set_message("Downloading");
+            let mut reader = stream.reader.track_with(bar);
+
+            match std::io::copy(&mut reader, &mut stdout()) {
+                Ok(_) => reader.bar.finish_with_message("Finished download."),
+                Err(e) => {
+                    reader.bar.abandon_with_message(format!("Failed: {}", e));
+                    return Err(e.into());
+                }
+            }
+        }
+        DownloadLocation::File(path) => with_retry(max_retries, || {
+            download_to_file(
+                client,
+                project,
+                version,
+                build,
+                download,
+                checksum,
+                path,
+                verify_signature,
+                multi,
+            )
+        })?,
+    }
+
+    Ok(())
+}
+
+/// Downloads `download` to the `<name>.part` sibling of `path`, resuming
+/// from whatever bytes are already in that file (if any) via a `Range`
+/// request, and only renames it into place once the accumulated digest
+/// matches (and, if `verify_signature` names a keyring, once a detached
+/// signature at `<path>.sig` verifies against it too). A caller retrying
+/// this function after a transient failure re-enters the same resume logic
+/// rather than discarding whatever was already fetched.
+fn download_to_file(
+    client: &PaperClient,
+    project: &str,
+    version: &str,
     build: i32,
     download: &Download,
-) -> Result<Vec<u8>, anyhow::Error> {
-    let res = attohttpc::get(format!(
-        "{}/projects/{}/versions/{}/builds/{}/downloads/{}",
-        PAPER_BASE, args.project, version, build, download.name
-    ))
-    .send()?
-    .error_for_status()?;
-    let bar_length = res.headers().get("Content-length").and_then(|len| {
-        len.to_str()
-            .ok()
-            .and_then(|len_str| len_str.parse::<u64>().ok())
+    checksum: &Checksum,
+    path: &Path,
+    verify_signature: Option<&Path>,
+    multi: Option<&MultiProgress>,
+) -> Result<(), anyhow::Error> {
+    let part_path = part_path(path);
+
+    let existing_len = part_path.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut hasher = checksum.hasher();
+    if existing_len > 0 {
+        std::io::copy(&mut File::open(&part_path)?, &mut hasher)?;
+    }
+
+    let stream = client.download_stream(
+        project,
+        version,
+        build,
+        download,
+        checksum,
+        existing_len,
+        Some(hasher),
+    )?;
+
+    let total_len = stream.content_length.map(|len| {
+        if stream.resumed {
+            existing_len + len
+        } else {
+            len
+        }
     });
+    let bar = new_progress_bar(multi, total_len);
+    bar.set_message("Downloading");
+    if stream.resumed {
+        bar.inc(existing_len);
+    }
 
-    let bar = new_progress_bar(bar_length);
-    bar.set_message("Downloading to memory");
+    let mut reader = stream.reader.track_with(bar);
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(stream.resumed)
+        .truncate(!stream.resumed)
+        .open(&part_path)?;
+
+    match std::io::copy(&mut reader, &mut file) {
+        Ok(_) => {}
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::InvalidData {
+                // Digest mismatch: the bytes on disk are simply wrong, so
+                // there's nothing for a resume to continue from.
+                let _ = std::fs::remove_file(&part_path);
+            }
+            reader.bar.abandon_with_message(format!("Failed: {}", e));
+            return Err(e.into());
+        }
+    }
 
-    let mut real_reader = res.track_with(bar);
+    if let Some(keyring_path) = verify_signature {
+        if let Err(e) = verify_detached_signature(&part_path, &sig_path(path), keyring_path) {
+            let _ = std::fs::remove_file(&part_path);
+            reader
+                .bar
+                .abandon_with_message("Signature check failed! :(");
+            return Err(e);
+        }
+    }
+
+    reader.bar.finish_with_message("Finished download.");
+
+    std::fs::rename(&part_path, path).with_context(|| {
+        format!(
+            "Failed to move {} into place at {}",
+            part_path.display(),
+            path.display()
+        )
+    })?;
+
+    Ok(())
+}
+
+/// The sibling `<name>.part` path a download is staged at before being
+/// atomically renamed into place once its digest has been verified.
+fn part_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+    path.with_file_name(file_name)
+}
 
-    let mut bytes: Vec<u8> = vec![];
-    std::io::copy(&mut real_reader, &mut bytes)?;
-    real_reader.bar.finish_with_message("Finished download.");
-    Ok(bytes)
+/// The sibling `<name>.sig` path a detached signature for `path` is
+/// expected at when `--verify-signature` is given.
+fn sig_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".sig");
+    path.with_file_name(file_name)
 }
 
 fn check_file_hash(
-    download_hash: &Vec<u8>,
+    checksum: &Checksum,
     download_location: &Path,
+    multi: Option<&MultiProgress>,
 ) -> Result<bool, anyhow::Error> {
-    let bar = new_progress_bar(download_location.metadata().map(|m| m.len()).ok());
+    let bar = new_progress_bar(multi, download_location.metadata().map(|m| m.len()).ok());
     bar.set_message("Checking if file is the latest build");
     let mut file_reader = File::open(download_location)?.track_with(bar);
 
     let disk_hash = {
-        let mut sha = sha2::Sha256::new();
-        std::io::copy(&mut file_reader, &mut sha)?;
-        sha.finalize().to_vec()
+        let mut hasher = checksum.hasher();
+        std::io::copy(&mut file_reader, &mut hasher)?;
+        hasher.finalize()
     };
-    let is_good = download_hash == &disk_hash;
+    let is_good = checksum.matches(&disk_hash);
 
     file_reader.bar.finish_with_message(if is_good {
         "File is latest"
@@ -212,47 +550,19 @@ fn check_file_hash(
     Ok(is_good)
 }
 
-fn check_mem_hash(download_hash: &Vec<u8>, bytes: &Vec<u8>) {
-    let bar = new_progress_bar(Some(bytes.len() as u64));
-    bar.set_message("Validating");
-    let mut bytes_reader = bytes.as_slice().track_with(bar);
-
-    let memory_hash = {
-        let mut sha = sha2::Sha256::new();
-        std::io::copy(&mut bytes_reader, &mut sha).unwrap();
-        sha.finalize().to_vec()
-    };
-    let is_good = download_hash == &memory_hash;
-
-    bytes_reader
-        .bar
-        .finish_with_message(if is_good { "Valid!" } else { "Invalid! :(" });
-
-    if !is_good {
-        panic!(
-            "Failed digest check, given {}, got {}",
-            hex::encode(&download_hash),
-            hex::encode(&memory_hash)
-        );
-    }
-}
-
 fn determine_version(
+    client: &PaperClient,
     project_data: &ProjectData,
-    version: &String,
+    version: &str,
 ) -> Result<String, anyhow::Error> {
-    if project_data.version_groups.contains(&version) {
-        let group_data: VersionGroupData = do_get_json(format!(
-            "{}/projects/{}/version_group/{}",
-            PAPER_BASE, project_data.project_id, version
-        ))
-        .expect("Failed to get version group data");
+    if project_data.version_groups.iter().any(|g| g == version) {
+        let group_data = client.version_group(&project_data.project_id, version)?;
         if let Some(g) = group_data.versions.into_iter().last() {
             return Ok(g);
         }
     }
-    if project_data.versions.contains(&version) {
-        Ok(version.clone())
+    if project_data.versions.iter().any(|v| v == version) {
+        Ok(version.to_string())
     } else {
         Err(anyhow::anyhow!(
             "{} is not a known version or (part of a) version group",
@@ -260,39 +570,3 @@ fn determine_version(
         ))
     }
 }
-
-fn do_get_json<T: DeserializeOwned, U: AsRef<str>>(url: U) -> Result<T, anyhow::Error> {
-    attohttpc::get(url)
-        .send()
-        .and_then(|x| x.error_for_status())
-        .and_then(|x| x.json())
-        .context("Failed to download JSON")
-}
-
-#[derive(Deserialize)]
-struct ProjectData {
-    project_id: String,
-    version_groups: Vec<String>,
-    versions: Vec<String>,
-}
-
-#[derive(Deserialize)]
-struct VersionGroupData {
-    versions: Vec<String>,
-}
-
-#[derive(Deserialize)]
-struct VersionData {
-    builds: Vec<i32>,
-}
-
-#[derive(Deserialize)]
-struct BuildData {
-    downloads: HashMap<String, Download>,
-}
-
-#[derive(Deserialize)]
-struct Download {
-    name: String,
-    sha256: String,
-}
@@ -1,8 +1,11 @@
 use std::io::Read;
 
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 
-pub fn new_progress_bar(bar_length: Option<u64>) -> ProgressBar {
+/// Builds a progress bar for a single download. When `multi` is given, the
+/// bar is added to that `MultiProgress` and stacks alongside the other
+/// in-flight downloads' bars instead of drawing straight to stderr.
+pub fn new_progress_bar(multi: Option<&MultiProgress>, bar_length: Option<u64>) -> ProgressBar {
     let bar_style = (match bar_length {
         Some(_) => ProgressStyle::default_bar(),
         None => ProgressStyle::default_spinner(),
@@ -14,7 +17,13 @@ pub fn new_progress_bar(bar_length: Option<u64>) -> ProgressBar {
     .progress_chars("#|-");
 
     let bar = ProgressBar::new(bar_length.unwrap_or(!0)).with_style(bar_style);
-    bar.set_draw_target(ProgressDrawTarget::stderr_with_hz(5));
+    let bar = match multi {
+        Some(multi) => multi.add(bar),
+        None => {
+            bar.set_draw_target(ProgressDrawTarget::stderr_with_hz(5));
+            bar
+        }
+    };
     bar.tick();
     bar
 }
@@ -0,0 +1,69 @@
+use std::thread::sleep;
+use std::time::Duration;
+
+use rand::Rng;
+
+const BASE_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Runs `op`, retrying on transient failures (connection resets, timeouts,
+/// and 5xx/429 responses) with exponential backoff plus jitter: base 500ms,
+/// doubling each attempt, capped at 30s. Gives up and returns the last error
+/// once `max_retries` attempts have been made, or immediately for errors
+/// that aren't transient.
+pub fn with_retry<T>(
+    max_retries: u32,
+    mut op: impl FnMut() -> Result<T, anyhow::Error>,
+) -> Result<T, anyhow::Error> {
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt < max_retries && is_retryable(&e) => {
+                let delay = backoff_delay(attempt);
+                eprintln!(
+                    "Transient error ({}), retrying in {:.1}s (attempt {}/{})",
+                    e,
+                    delay.as_secs_f32(),
+                    attempt + 1,
+                    max_retries
+                );
+                sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn backoff_delay(attempt: u32) -> Duration {
+    let exp = BASE_DELAY.saturating_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX));
+    let jitter = Duration::from_millis(rand::thread_rng().gen_range(0..=100));
+    exp.min(MAX_DELAY) + jitter
+}
+
+fn is_retryable(err: &anyhow::Error) -> bool {
+    if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+        return is_transient_io_error(io_err);
+    }
+    match err.downcast_ref::<attohttpc::Error>() {
+        Some(attohttpc::Error::Io(io_err)) => is_transient_io_error(io_err),
+        Some(attohttpc::Error::StatusCode(status)) => {
+            status.is_server_error() || status.as_u16() == 429
+        }
+        _ => false,
+    }
+}
+
+/// A connection failure that's worth retrying rather than one (like a
+/// digest mismatch surfacing as `ErrorKind::InvalidData`) that will just
+/// happen again.
+fn is_transient_io_error(io_err: &std::io::Error) -> bool {
+    matches!(
+        io_err.kind(),
+        std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::TimedOut
+            | std::io::ErrorKind::UnexpectedEof
+    )
+}
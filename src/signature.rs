@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::Context;
+use gpgrv::Keyring;
+
+/// Checks `artifact_path` against a detached OpenPGP signature at
+/// `signature_path`, trusting only the keys in `keyring_path`. This mirrors
+/// how fapt verifies its downloads with `gpgrv` instead of shelling out to
+/// `gpg`, so it works without a system GnuPG install.
+pub fn verify_detached_signature(
+    artifact_path: &Path,
+    signature_path: &Path,
+    keyring_path: &Path,
+) -> Result<(), anyhow::Error> {
+    let mut keyring = Keyring::new();
+    keyring
+        .append_keys_from(
+            File::open(keyring_path)
+                .with_context(|| format!("Failed to open keyring at {}", keyring_path.display()))?,
+        )
+        .with_context(|| format!("Failed to parse keyring at {}", keyring_path.display()))?;
+
+    let artifact = File::open(artifact_path).with_context(|| {
+        format!(
+            "Failed to open {} for signature verification",
+            artifact_path.display()
+        )
+    })?;
+    let signature = File::open(signature_path)
+        .with_context(|| format!("Failed to open signature at {}", signature_path.display()))?;
+
+    gpgrv::verify_detached_sig(signature, artifact, &keyring).with_context(|| {
+        format!(
+            "Signature verification failed for {}",
+            artifact_path.display()
+        )
+    })
+}